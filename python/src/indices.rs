@@ -1,10 +1,23 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-FileCopyrightText: Copyright The Lance Authors
 
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
 use arrow::pyarrow::{PyArrowType, ToPyArrow};
-use arrow_array::{Array, FixedSizeListArray};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float32Type, UInt64Type};
+use arrow_array::{Array, FixedSizeListArray, RecordBatch, UInt32Array};
 use arrow_data::ArrayData;
-use lance::index::vector::ivf::builder::write_vector_storage;
+use arrow_schema::{DataType, Field, Schema};
+use datafusion::physical_plan::SendableRecordBatchStream;
+use fixedbitset::FixedBitSet;
+use futures::TryStreamExt;
+use parquet::arrow::AsyncArrowWriter;
+use parquet::file::properties::WriterProperties;
+use parquet::format::KeyValue;
+use lance::index::vector::hnsw::HnswIndexMetadata;
+use lance::index::vector::ivf::builder::{read_vector_storage, write_vector_storage};
 use lance::index::vector::ivf::io::write_pq_partitions;
 use lance_index::vector::ivf::shuffler::{shuffle_vectors, load_partitioned_shuffles};
 use lance_index::vector::{
@@ -15,8 +28,11 @@ use lance_linalg::distance::DistanceType;
 use pyo3::{
     pyfunction,
     types::{PyList, PyModule},
-    wrap_pyfunction, PyObject, PyResult, Python
+    wrap_pyfunction, PyObject, PyResult, Python, ToPyObject
 };
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use tokio::io::AsyncWriteExt;
 
 use crate::fragment::FileFragment;
 use crate::{dataset::Dataset, error::PythonErrorExt, file::object_store_from_uri_or_path, RT};
@@ -25,6 +41,132 @@ use lance_file::format::MAGIC;
 use lance_index::pb::Index;
 use lance::index::vector::ivf::IvfPQIndexMetadata;
 
+/// Manifest suffix appended to `shuffle_output_root_filename` when writing the
+/// sidecar integrity manifest for a shuffle's partition files.
+const SHUFFLE_MANIFEST_SUFFIX: &str = ".manifest.json";
+
+/// One partition file's expected checksum and size, as recorded in a shuffle manifest.
+#[derive(Serialize, Deserialize)]
+struct PartitionManifestEntry {
+    filename: String,
+    sha3_256: String,
+    length: u64,
+}
+
+/// Hex-encodes `bytes`, used both for SHA3 digests and for embedding binary arrays
+/// (IVF centroids, PQ codebook) into Parquet string key-value metadata.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Default PQ bit width, matching the value every pyfunction hard-coded before
+/// `num_bits` became configurable.
+const DEFAULT_PQ_NUM_BITS: u32 = 8;
+
+/// Validates that `num_bits` is one of the widths the PQ code path actually supports,
+/// rejecting anything else here with a catchable error rather than panicking deep
+/// inside the bit-packing helpers.
+fn validate_pq_num_bits(num_bits: u32) -> PyResult<u32> {
+    match num_bits {
+        4 | 8 => Ok(num_bits),
+        other => Err(pyo3::exceptions::PyRuntimeError::new_err(format!(
+            "unsupported PQ num_bits: {other} (expected 4 or 8)"
+        ))),
+    }
+}
+
+const HASH_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Incrementally hashes the full contents of an already-opened reader with SHA3-256,
+/// returning the hex digest and total byte length.
+async fn hash_reader(reader: &dyn lance_io::traits::Reader) -> PyResult<(String, u64)> {
+    let size = reader.size().await.infer_error()? as u64;
+    let mut hasher = Sha3_256::new();
+    let mut offset = 0u64;
+    while offset < size {
+        let end = (offset + HASH_CHUNK_SIZE).min(size);
+        let chunk = reader.read_range(offset..end).await.infer_error()?;
+        hasher.update(&chunk);
+        offset = end;
+    }
+    let digest = to_hex(&hasher.finalize());
+    Ok((digest, size))
+}
+
+/// Hashes each partition file in `dir_path` and writes a sidecar manifest (filename,
+/// SHA3-256 digest, and byte length per entry) so a later load can detect truncation
+/// or corruption before it silently produces a broken index.
+///
+/// Returns the manifest's full `dir_path`-joined path, ready to pass straight back in
+/// as `verify_shuffle_manifest`'s (and `load_shuffled_vectors`'s) `manifest_path`.
+async fn write_shuffle_manifest(
+    dir_path: &str,
+    shuffle_output_root_filename: &str,
+    partition_files: &[String],
+) -> PyResult<String> {
+    let (obj_store, _) = object_store_from_uri_or_path(dir_path).await?;
+
+    let mut entries = Vec::with_capacity(partition_files.len());
+    for filename in partition_files {
+        let (_, path) = object_store_from_uri_or_path(&format!("{dir_path}/{filename}")).await?;
+        let reader = obj_store.open(&path).await.infer_error()?;
+        let (sha3_256, length) = hash_reader(reader.as_ref()).await?;
+        entries.push(PartitionManifestEntry {
+            filename: filename.clone(),
+            sha3_256,
+            length,
+        });
+    }
+
+    let manifest_filename = format!("{shuffle_output_root_filename}{SHUFFLE_MANIFEST_SUFFIX}");
+    let manifest_path = format!("{dir_path}/{manifest_filename}");
+    let (_, manifest_obj_path) = object_store_from_uri_or_path(&manifest_path).await?;
+    let mut writer = obj_store.create(&manifest_obj_path).await.infer_error()?;
+    let body = serde_json::to_vec(&entries).infer_error()?;
+    writer.write_all(&body).await.infer_error()?;
+    writer.shutdown().await.infer_error()?;
+
+    Ok(manifest_path)
+}
+
+/// Re-hashes each partition file in `dir_path` as it streams and fails fast with a
+/// clear error if a digest or length doesn't match the manifest written by
+/// `write_shuffle_manifest`.
+async fn verify_shuffle_manifest(
+    dir_path: &str,
+    manifest_path: &str,
+    filenames: &[String],
+) -> PyResult<()> {
+    let (manifest_store, manifest_obj_path) = object_store_from_uri_or_path(manifest_path).await?;
+    let manifest_reader = manifest_store.open(&manifest_obj_path).await.infer_error()?;
+    let manifest_len = manifest_reader.size().await.infer_error()? as u64;
+    let manifest_bytes = manifest_reader.read_range(0..manifest_len).await.infer_error()?;
+    let entries: Vec<PartitionManifestEntry> = serde_json::from_slice(&manifest_bytes).infer_error()?;
+    let by_filename: std::collections::HashMap<_, _> =
+        entries.into_iter().map(|e| (e.filename.clone(), e)).collect();
+
+    let (obj_store, _) = object_store_from_uri_or_path(dir_path).await?;
+    for filename in filenames {
+        let entry = by_filename.get(filename).ok_or_else(|| {
+            pyo3::exceptions::PyRuntimeError::new_err(format!(
+                "shuffle manifest {manifest_path} has no entry for partition file {filename}"
+            ))
+        })?;
+        let (_, path) = object_store_from_uri_or_path(&format!("{dir_path}/{filename}")).await?;
+        let reader = obj_store.open(&path).await.infer_error()?;
+        let (sha3_256, length) = hash_reader(reader.as_ref()).await?;
+        if sha3_256 != entry.sha3_256 || length != entry.length {
+            return Err(pyo3::exceptions::PyRuntimeError::new_err(format!(
+                "shuffle partition file {filename} failed integrity check: expected sha3-256 \
+                 {} ({} bytes), found {} ({} bytes)",
+                entry.sha3_256, entry.length, sha3_256, length
+            )));
+        }
+    }
+
+    Ok(())
+}
+
 async fn do_train_ivf_model(
     dataset: &Dataset,
     column: &str,
@@ -88,6 +230,7 @@ async fn do_train_pq_model(
     column: &str,
     dimension: usize,
     num_subvectors: u32,
+    num_bits: u32,
     distance_type: &str,
     sample_rate: u32,
     max_iters: u32,
@@ -97,7 +240,7 @@ async fn do_train_pq_model(
     let distance_type = DistanceType::try_from(distance_type).unwrap();
     let params = PQBuildParams {
         num_sub_vectors: num_subvectors as usize,
-        num_bits: 8,
+        num_bits: num_bits as usize,
         max_iters: max_iters as usize,
         sample_rate: sample_rate as usize,
         ..Default::default()
@@ -127,7 +270,9 @@ fn train_pq_model(
     sample_rate: u32,
     max_iters: u32,
     ivf_centroids: PyArrowType<ArrayData>,
+    num_bits: Option<u32>,
 ) -> PyResult<PyObject> {
+    let num_bits = validate_pq_num_bits(num_bits.unwrap_or(DEFAULT_PQ_NUM_BITS))?;
     let ivf_centroids = ivf_centroids.0;
     let ivf_centroids = FixedSizeListArray::from(ivf_centroids);
     let ivf_model = IvfModel {
@@ -142,6 +287,7 @@ fn train_pq_model(
             column,
             dimension,
             num_subvectors,
+            num_bits,
             distance_type,
             sample_rate,
             max_iters,
@@ -151,6 +297,182 @@ fn train_pq_model(
     codebook.to_pyarrow(py)
 }
 
+/// Serializes `array` to an Arrow IPC stream and hex-encodes it, so it can round-trip
+/// through a Parquet file's string-valued key-value metadata.
+fn array_to_hex(array: &dyn Array) -> PyResult<String> {
+    let field = Field::new("value", array.data_type().clone(), array.is_nullable());
+    let schema = std::sync::Arc::new(Schema::new(vec![field]));
+    let batch = RecordBatch::try_new(schema.clone(), vec![array.slice(0, array.len())]).infer_error()?;
+    let mut buf = Vec::new();
+    {
+        let mut writer = arrow_ipc::writer::StreamWriter::try_new(&mut buf, &schema).infer_error()?;
+        writer.write(&batch).infer_error()?;
+        writer.finish().infer_error()?;
+    }
+    Ok(to_hex(&buf))
+}
+
+/// Finds the index of the IVF centroid closest to `vector` under `distance_type`.
+fn nearest_partition(vector: &[f32], centroids: &FixedSizeListArray, distance_type: DistanceType) -> u32 {
+    let dist_fn = distance_type.func();
+    let dim = centroids.value_length() as usize;
+    let values = centroids.values().as_primitive::<Float32Type>().values();
+    (0..centroids.len())
+        .map(|i| (i as u32, dist_fn(vector, &values[i * dim..(i + 1) * dim])))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+        .map(|(idx, _)| idx)
+        .unwrap_or(0)
+}
+
+/// Encodes `vector` into its PQ code, one centroid index per subvector. Each code is
+/// in `0..2^pq.num_bits`, i.e. bounded by the bit width `pq` was built with.
+fn pq_encode_vector(vector: &[f32], pq: &ProductQuantizer, distance_type: DistanceType) -> Vec<u8> {
+    let num_sub_vectors = pq.num_sub_vectors;
+    let sub_dim = pq.dimension / num_sub_vectors;
+    let num_centroids = 1u32 << pq.num_bits;
+    let dist_fn = distance_type.func();
+    (0..num_sub_vectors)
+        .map(|j| {
+            let sub_vector = &vector[j * sub_dim..(j + 1) * sub_dim];
+            (0..num_centroids)
+                .map(|code| code as u8)
+                .map(|code| (code, dist_fn(sub_vector, pq_centroid(pq, num_sub_vectors, j, code))))
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+                .map(|(code, _)| code)
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// Packs a vector of `num_bits`-wide codes as tightly as bytes allow. At 8 bits this
+/// is a no-op passthrough; at 4 bits two codes are packed per byte (low nibble first),
+/// roughly halving the on-disk size of the transformed storage.
+fn pack_pq_codes(codes: &[u8], num_bits: u32) -> Vec<u8> {
+    match num_bits {
+        8 => codes.to_vec(),
+        4 => codes
+            .chunks(2)
+            .map(|pair| {
+                let lo = pair[0] & 0x0F;
+                let hi = pair.get(1).copied().unwrap_or(0) & 0x0F;
+                lo | (hi << 4)
+            })
+            .collect(),
+        other => unreachable!("unsupported PQ num_bits: {other}"),
+    }
+}
+
+/// Byte width of a packed PQ code for `num_sub_vectors` subvectors at `num_bits` each.
+fn packed_code_width(num_sub_vectors: usize, num_bits: u32) -> usize {
+    match num_bits {
+        8 => num_sub_vectors,
+        4 => num_sub_vectors.div_ceil(2),
+        other => unreachable!("unsupported PQ num_bits: {other}"),
+    }
+}
+
+/// Writes PQ-transformed vectors as an Arrow-backed Parquet file: one row per input
+/// vector with its row id, assigned IVF partition, and packed PQ code, plus the IVF
+/// centroids, PQ codebook, and quantization parameters embedded in the file's
+/// key-value metadata so the codebook can be recovered without touching Lance
+/// internals (e.g. to round-trip into `load_shuffled_vectors`).
+async fn write_transformed_vectors_as_parquet(
+    mut transform_input: SendableRecordBatchStream,
+    ivf_centroids: &FixedSizeListArray,
+    pq_model: &ProductQuantizer,
+    distance_type: DistanceType,
+    column: &str,
+    dst_uri: &str,
+) -> PyResult<()> {
+    let code_width = packed_code_width(pq_model.num_sub_vectors, pq_model.num_bits as u32);
+    let code_field = Field::new("pq_code", DataType::FixedSizeBinary(code_width as i32), false);
+    let schema = std::sync::Arc::new(Schema::new(vec![
+        Field::new("row_id", DataType::UInt64, false),
+        Field::new("partition_id", DataType::UInt32, false),
+        code_field,
+    ]));
+
+    let kv_metadata = vec![
+        KeyValue::new("lance:ivf_centroids".to_string(), array_to_hex(ivf_centroids)?),
+        KeyValue::new(
+            "lance:pq_codebook".to_string(),
+            array_to_hex(&pq_model.codebook)?,
+        ),
+        KeyValue::new(
+            "lance:num_subvectors".to_string(),
+            pq_model.num_sub_vectors.to_string(),
+        ),
+        KeyValue::new("lance:num_bits".to_string(), pq_model.num_bits.to_string()),
+        KeyValue::new("lance:dimension".to_string(), pq_model.dimension.to_string()),
+        KeyValue::new("lance:distance_type".to_string(), distance_type.to_string()),
+    ];
+    let props = WriterProperties::builder()
+        .set_key_value_metadata(Some(kv_metadata))
+        .build();
+
+    let (obj_store, path) = object_store_from_uri_or_path(dst_uri).await?;
+    let sink = obj_store.create(&path).await.infer_error()?;
+    let mut writer = AsyncArrowWriter::try_new(sink, schema.clone(), Some(props)).infer_error()?;
+
+    while let Some(batch) = transform_input.try_next().await.infer_error()? {
+        let row_ids = batch
+            .column_by_name(lance_core::ROW_ID)
+            .unwrap()
+            .as_primitive::<UInt64Type>()
+            .clone();
+        let vectors = batch
+            .column_by_name(column)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<FixedSizeListArray>()
+            .unwrap();
+
+        let mut partitions = Vec::with_capacity(vectors.len());
+        let mut codes = Vec::with_capacity(vectors.len());
+        for i in 0..vectors.len() {
+            let vector = vectors.value(i);
+            let vector = vector.as_primitive::<Float32Type>();
+            let vector = vector.values();
+            partitions.push(nearest_partition(vector, ivf_centroids, distance_type));
+            let code = pq_encode_vector(vector, pq_model, distance_type);
+            codes.push(Some(pack_pq_codes(&code, pq_model.num_bits as u32)));
+        }
+
+        let partition_array = UInt32Array::from(partitions);
+        let code_array = arrow_array::FixedSizeBinaryArray::try_from_sparse_iter_with_size(
+            codes.into_iter(),
+            code_width as i32,
+        )
+        .infer_error()?;
+
+        let out_batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                std::sync::Arc::new(row_ids),
+                std::sync::Arc::new(partition_array),
+                std::sync::Arc::new(code_array),
+            ],
+        )
+        .infer_error()?;
+        writer.write(&out_batch).await.infer_error()?;
+    }
+
+    writer.close().await.infer_error()?;
+    Ok(())
+}
+
+/// Validates `transform_vectors`' output `format` against the set of writers
+/// `do_transform_vectors` actually knows how to dispatch to, rejecting typos
+/// (e.g. `"Parquet"`) instead of silently falling back to the default Lance writer.
+fn validate_transform_format(format: &str) -> PyResult<&str> {
+    match format {
+        "lance" | "parquet" => Ok(format),
+        other => Err(pyo3::exceptions::PyRuntimeError::new_err(format!(
+            "unsupported transform_vectors format: {other} (expected \"lance\" or \"parquet\")"
+        ))),
+    }
+}
+
 async fn do_transform_vectors(
     dataset: &Dataset,
     column: &str,
@@ -159,8 +481,8 @@ async fn do_transform_vectors(
     pq_model: ProductQuantizer,
     dst_uri: &str,
     fragments: Vec<FileFragment>,
+    format: &str,
 ) -> PyResult<()> {
-    let num_rows = dataset.ds.count_rows(None).await.infer_error()?;
     let fragments = fragments.iter().map(|item| item.metadata().inner).collect();
     let transform_input = dataset
         .ds
@@ -174,6 +496,21 @@ async fn do_transform_vectors(
         .await
         .infer_error()?;
 
+    if format == "parquet" {
+        return write_transformed_vectors_as_parquet(
+            transform_input,
+            &ivf_centroids,
+            &pq_model,
+            distance_type,
+            column,
+            dst_uri,
+        )
+        .await;
+    }
+
+    // Only the `lance`-format writer below needs the total row count; skip the extra
+    // full-table count for the `parquet` path, which returns above.
+    let num_rows = dataset.ds.count_rows(None).await.infer_error()?;
     let (obj_store, path) = object_store_from_uri_or_path(dst_uri).await?;
     let writer = obj_store.create(&path).await.infer_error()?;
     write_vector_storage(
@@ -203,7 +540,11 @@ pub fn transform_vectors(
     pq_codebook: PyArrowType<ArrayData>,
     dst_uri: &str,
     fragments: Vec<FileFragment>,
+    format: Option<&str>,
+    num_bits: Option<u32>,
 ) -> PyResult<()> {
+    let num_bits = validate_pq_num_bits(num_bits.unwrap_or(DEFAULT_PQ_NUM_BITS))?;
+    let format = validate_transform_format(format.unwrap_or("lance"))?;
     let ivf_centroids = ivf_centroids.0;
     let ivf_centroids = FixedSizeListArray::from(ivf_centroids);
     let codebook = pq_codebook.0;
@@ -211,7 +552,7 @@ pub fn transform_vectors(
     let distance_type = DistanceType::try_from(distance_type).unwrap();
     let pq = ProductQuantizer::new(
         num_subvectors as usize,
-        /*num_bits=*/ 8,
+        num_bits as usize,
         dimension,
         codebook,
         distance_type,
@@ -226,20 +567,42 @@ pub fn transform_vectors(
             pq,
             dst_uri,
             fragments,
+            format,
         ),
     )?
 }
 
+/// Default bound on the number of `(partition_id, batch)` pairs buffered between the
+/// shuffle's reader threads and per-partition writer tasks.
+const DEFAULT_SHUFFLE_CHANNEL_CAPACITY: usize = 1024;
+/// Default number of concurrent reader threads decoding the unsorted input files.
+const DEFAULT_SHUFFLE_NUM_WORKERS: usize = 4;
+
 async fn do_shuffle_transformed_vectors(
     unsorted_filenames: Vec<String>,
     dir_path: &str,
     ivf_centroids: FixedSizeListArray,
     shuffle_output_root_filename: &str,
-) -> PyResult<Vec<String>> {
-    let partition_files = shuffle_vectors(unsorted_filenames, dir_path, ivf_centroids, shuffle_output_root_filename)
-        .await
-        .infer_error()?;
-    Ok(partition_files)
+    channel_capacity: usize,
+    num_workers: usize,
+) -> PyResult<(Vec<String>, String)> {
+    let mut partition_files = shuffle_vectors(
+        unsorted_filenames,
+        dir_path,
+        ivf_centroids,
+        shuffle_output_root_filename,
+        channel_capacity,
+        num_workers,
+    )
+    .await
+    .infer_error()?;
+    // `shuffle_vectors` hands partitions to writer tasks as reader threads produce
+    // them, so completion order depends on scheduling; sort so callers (and the
+    // manifest below) always see a deterministic partition-file ordering.
+    partition_files.sort();
+    let manifest_filename =
+        write_shuffle_manifest(dir_path, shuffle_output_root_filename, &partition_files).await?;
+    Ok((partition_files, manifest_filename))
 }
 
 #[pyfunction]
@@ -250,19 +613,28 @@ pub fn shuffle_transformed_vectors(
     dir_path: &str,
     ivf_centroids: PyArrowType<ArrayData>,
     shuffle_output_root_filename: &str,
+    channel_capacity: Option<usize>,
+    num_workers: Option<usize>,
 ) -> PyResult<PyObject> {
     let ivf_centroids = ivf_centroids.0;
     let ivf_centroids = FixedSizeListArray::from(ivf_centroids);
 
     let result = RT.block_on(
         None,
-        do_shuffle_transformed_vectors(unsorted_filenames, dir_path, ivf_centroids, shuffle_output_root_filename),
+        do_shuffle_transformed_vectors(
+            unsorted_filenames,
+            dir_path,
+            ivf_centroids,
+            shuffle_output_root_filename,
+            channel_capacity.unwrap_or(DEFAULT_SHUFFLE_CHANNEL_CAPACITY),
+            num_workers.unwrap_or(DEFAULT_SHUFFLE_NUM_WORKERS),
+        ),
     )?;
 
     match result {
-        Ok(partition_files) => {
+        Ok((partition_files, manifest_filename)) => {
             let py_list = PyList::new(py, partition_files);
-            Ok(py_list.into())
+            Ok((py_list, manifest_filename).to_object(py))
         }
         Err(e) => Err(pyo3::exceptions::PyRuntimeError::new_err(e.to_string())),
     }
@@ -275,7 +647,12 @@ async fn do_load_shuffled_vectors(
     column: &str,
     mut ivf_model: IvfModel,
     pq_model: ProductQuantizer,
+    manifest_path: Option<&str>,
 ) -> PyResult<()> {
+    if let Some(manifest_path) = manifest_path {
+        verify_shuffle_manifest(dir_path, manifest_path, &filenames).await?;
+    }
+
     let (obj_store, path) = object_store_from_uri_or_path(dir_path).await?;
     let streams = load_partitioned_shuffles(path.clone(), filenames).await.infer_error()?;
 
@@ -315,9 +692,12 @@ pub fn load_shuffled_vectors(
     ivf_centroids: PyArrowType<ArrayData>,
     pq_codebook: PyArrowType<ArrayData>,
     pq_dimension: usize,
-    num_subvectors: u32, 
+    num_subvectors: u32,
     distance_type: &str,
+    manifest_path: Option<&str>,
+    num_bits: Option<u32>,
 ) -> PyResult<()> {
+    let num_bits = validate_pq_num_bits(num_bits.unwrap_or(DEFAULT_PQ_NUM_BITS))?;
     let ivf_centroids = ivf_centroids.0;
     let ivf_centroids = FixedSizeListArray::from(ivf_centroids);
 
@@ -333,7 +713,7 @@ pub fn load_shuffled_vectors(
     let distance_type = DistanceType::try_from(distance_type).unwrap();
     let pq_model = ProductQuantizer::new(
         num_subvectors as usize,
-        /*num_bits=*/ 8,
+        num_bits as usize,
         pq_dimension,
         codebook,
         distance_type,
@@ -341,7 +721,248 @@ pub fn load_shuffled_vectors(
 
     RT.block_on(
         None,
-        do_load_shuffled_vectors(filenames, dir_path, dataset, column, ivf_model, pq_model),
+        do_load_shuffled_vectors(filenames, dir_path, dataset, column, ivf_model, pq_model, manifest_path),
+    )?
+}
+
+/// A candidate/result entry in the HNSW beam search, ordered by distance to the query.
+#[derive(Clone, Copy, Debug)]
+struct Neighbor {
+    id: u32,
+    dist: f32,
+}
+
+impl PartialEq for Neighbor {
+    fn eq(&self, other: &Self) -> bool {
+        self.dist == other.dist
+    }
+}
+impl Eq for Neighbor {}
+impl PartialOrd for Neighbor {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Neighbor {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist.partial_cmp(&other.dist).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// A flat, single-layer navigable small-world graph over PQ-compressed vectors.
+///
+/// `neighbors[i]` holds the out-edges of node `i`, capped at `max_edges` per node, each
+/// paired with its distance from node `i` so `connect` can evict the farthest edge
+/// instead of the oldest one when a node's edge list fills up.
+struct HnswGraph {
+    neighbors: Vec<Vec<Neighbor>>,
+    max_edges: usize,
+}
+
+impl HnswGraph {
+    fn new(num_nodes: usize, max_edges: usize) -> Self {
+        Self {
+            neighbors: vec![Vec::with_capacity(max_edges); num_nodes],
+            max_edges,
+        }
+    }
+
+    /// Beam search for the `ef` nearest neighbors of a query reachable from `entry_points`.
+    ///
+    /// Keeps a candidate min-heap (closest unexpanded node first) and a result max-heap
+    /// capped at `ef` entries. Each step pops the nearest unexpanded candidate, stops once
+    /// its distance exceeds the farthest entry currently in the result heap, and otherwise
+    /// scores its unvisited neighbors, pushing any that improve the result set into both
+    /// heaps and evicting the result heap's max when it overflows `ef`.
+    fn search(&self, dist_to: impl Fn(u32) -> f32, entry_points: &[u32], ef: usize) -> BinaryHeap<Neighbor> {
+        let mut visited = FixedBitSet::with_capacity(self.neighbors.len());
+        let mut candidates: BinaryHeap<Reverse<Neighbor>> = BinaryHeap::new();
+        let mut results: BinaryHeap<Neighbor> = BinaryHeap::new();
+
+        for &entry in entry_points {
+            if visited.put(entry as usize) {
+                continue;
+            }
+            let dist = dist_to(entry);
+            candidates.push(Reverse(Neighbor { id: entry, dist }));
+            results.push(Neighbor { id: entry, dist });
+        }
+
+        while let Some(Reverse(current)) = candidates.pop() {
+            if results.len() >= ef {
+                if let Some(farthest) = results.peek() {
+                    if current.dist > farthest.dist {
+                        break;
+                    }
+                }
+            }
+            for neighbor in self.neighbors[current.id as usize].iter().map(|n| n.id) {
+                if visited.put(neighbor as usize) {
+                    continue;
+                }
+                let dist = dist_to(neighbor);
+                let worth_keeping =
+                    results.len() < ef || results.peek().map(|n| dist < n.dist).unwrap_or(true);
+                if worth_keeping {
+                    candidates.push(Reverse(Neighbor { id: neighbor, dist }));
+                    results.push(Neighbor { id: neighbor, dist });
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results
+    }
+
+    /// Connects `node` to its nearest candidates, capped at `max_edges`, adding the
+    /// reverse edge on each neighbor so the graph stays navigable in both directions.
+    ///
+    /// Both directions evict by distance, not insertion order: `candidates` is already
+    /// sorted nearest-first for `node`'s own list, and a neighbor whose reverse-edge
+    /// list overflows keeps only its `max_edges` nearest edges, dropping the farthest
+    /// (which is the new edge itself when that one is the farthest), so no side ends up
+    /// stuck with a distant edge once a closer one is found.
+    fn connect(&mut self, node: u32, candidates: BinaryHeap<Neighbor>) {
+        let mut candidates = candidates.into_sorted_vec();
+        candidates.truncate(self.max_edges);
+        for c in candidates {
+            self.neighbors[node as usize].push(c);
+            let back_edges = &mut self.neighbors[c.id as usize];
+            back_edges.push(Neighbor { id: node, dist: c.dist });
+            if back_edges.len() > self.max_edges {
+                back_edges.sort();
+                back_edges.truncate(self.max_edges);
+            }
+        }
+    }
+}
+
+/// Reconstructs the `sub_dim`-length centroid for subvector `subvector_idx`'s `code`.
+fn pq_centroid(pq: &ProductQuantizer, num_sub_vectors: usize, subvector_idx: usize, code: u8) -> &[f32] {
+    let num_centroids = 1usize << pq.num_bits;
+    let sub_dim = pq.dimension / num_sub_vectors;
+    let row = subvector_idx * num_centroids + code as usize;
+    let values = pq.codebook.values().as_primitive::<Float32Type>().values();
+    &values[row * sub_dim..(row + 1) * sub_dim]
+}
+
+/// Approximate distance between two PQ codes, reusing `distance_type` per-subvector.
+fn pq_code_distance(pq: &ProductQuantizer, code_a: &[u8], code_b: &[u8], distance_type: DistanceType) -> f32 {
+    let dist_fn = distance_type.func();
+    let num_sub_vectors = code_a.len();
+    (0..num_sub_vectors)
+        .map(|j| {
+            let a = pq_centroid(pq, num_sub_vectors, j, code_a[j]);
+            let b = pq_centroid(pq, num_sub_vectors, j, code_b[j]);
+            dist_fn(a, b)
+        })
+        .sum()
+}
+
+/// Builds a single-layer HNSW graph by inserting PQ codes one at a time: each new node
+/// runs the beam search against the graph built so far to find its `ef_construction`
+/// nearest neighbors, then connects to the closest `max_edges` of them.
+fn construct_hnsw_graph(
+    codes: &[Vec<u8>],
+    pq: &ProductQuantizer,
+    distance_type: DistanceType,
+    max_edges: usize,
+    ef_construction: usize,
+) -> HnswGraph {
+    let mut graph = HnswGraph::new(codes.len(), max_edges);
+    let mut entry_points: Vec<u32> = Vec::new();
+
+    for i in 0..codes.len() {
+        if entry_points.is_empty() {
+            entry_points.push(i as u32);
+            continue;
+        }
+        let dist_to = |id: u32| pq_code_distance(pq, &codes[i], &codes[id as usize], distance_type);
+        let found = graph.search(dist_to, &entry_points, ef_construction);
+        entry_points = vec![i as u32];
+        graph.connect(i as u32, found);
+    }
+
+    graph
+}
+
+async fn do_build_hnsw_graph(
+    dataset: &Dataset,
+    column: &str,
+    storage_uri: &str,
+    max_edges: usize,
+    ef_construction: usize,
+    dst_uri: &str,
+) -> PyResult<()> {
+    let (storage_store, storage_path) = object_store_from_uri_or_path(storage_uri).await?;
+    let storage_reader = storage_store.open(&storage_path).await.infer_error()?;
+    let storage = read_vector_storage(storage_reader).await.infer_error()?;
+
+    let graph = construct_hnsw_graph(
+        storage.codes(),
+        storage.pq(),
+        storage.distance_type(),
+        max_edges,
+        ef_construction,
+    );
+
+    let (obj_store, path) = object_store_from_uri_or_path(dst_uri).await?;
+    let mut writer = obj_store.create(&path).await.infer_error()?;
+
+    // The persisted format only needs neighbor ids; per-edge distances are an
+    // in-memory aid for `connect`'s eviction choice during construction.
+    let neighbor_ids: Vec<Vec<u32>> = graph
+        .neighbors
+        .into_iter()
+        .map(|edges| edges.into_iter().map(|n| n.id).collect())
+        .collect();
+
+    let metadata = HnswIndexMetadata::new(
+        "hnsw_index".to_string(),
+        column.to_string(),
+        storage.dimension() as u32,
+        dataset.ds.version().version,
+        storage.distance_type(),
+        storage.row_ids().to_vec(),
+        neighbor_ids,
+        max_edges as u32,
+        ef_construction as u32,
+    );
+
+    let metadata = Index::try_from(&metadata).infer_error()?;
+    let pos = writer.write_protobuf(&metadata).await.infer_error()?;
+    writer.write_magics(pos, 0, 1, MAGIC).await.infer_error()?;
+    writer.shutdown().await.infer_error()?;
+
+    Ok(())
+}
+
+/// Builds a navigable small-world graph over the PQ-compressed storage produced by
+/// `transform_vectors`/`load_shuffled_vectors`, for use as a coarse layer or a
+/// standalone graph-based index.
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+pub fn build_hnsw_graph(
+    py: Python<'_>,
+    dataset: &Dataset,
+    column: &str,
+    storage_uri: &str,
+    max_edges: u32,
+    ef_construction: u32,
+    dst_uri: &str,
+) -> PyResult<()> {
+    RT.block_on(
+        Some(py),
+        do_build_hnsw_graph(
+            dataset,
+            column,
+            storage_uri,
+            max_edges as usize,
+            ef_construction as usize,
+            dst_uri,
+        ),
     )?
 }
 
@@ -352,6 +973,269 @@ pub fn register_indices(py: Python, m: &PyModule) -> PyResult<()> {
     indices.add_wrapped(wrap_pyfunction!(transform_vectors))?;
     indices.add_wrapped(wrap_pyfunction!(shuffle_transformed_vectors))?;
     indices.add_wrapped(wrap_pyfunction!(load_shuffled_vectors))?;
+    indices.add_wrapped(wrap_pyfunction!(build_hnsw_graph))?;
     m.add_submodule(indices)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a fresh, uniquely-named scratch directory under the system temp dir for
+    /// tests that need to exercise real object-store reads/writes.
+    fn test_scratch_dir(name: &str) -> std::path::PathBuf {
+        let unique = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!(
+            "lance_indices_test_{name}_{}_{unique}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn hash_reader_matches_direct_sha3_and_reports_size() {
+        let dir = test_scratch_dir("hash_reader");
+        let file_path = dir.join("data.bin");
+        let contents = b"the quick brown fox jumps over the lazy dog".repeat(100);
+        std::fs::write(&file_path, &contents).unwrap();
+
+        let mut expected_hasher = Sha3_256::new();
+        expected_hasher.update(&contents);
+        let expected_digest = to_hex(&expected_hasher.finalize());
+
+        let (digest, size) = RT
+            .block_on(None, async {
+                let (obj_store, path) =
+                    object_store_from_uri_or_path(file_path.to_str().unwrap()).await?;
+                let reader = obj_store.open(&path).await.infer_error()?;
+                hash_reader(reader.as_ref()).await
+            })
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(digest, expected_digest);
+        assert_eq!(size, contents.len() as u64);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn write_then_verify_shuffle_manifest_round_trips() {
+        let dir = test_scratch_dir("shuffle_manifest_roundtrip");
+        let dir_str = dir.to_str().unwrap().to_string();
+        std::fs::write(dir.join("part-0.lance"), b"partition zero").unwrap();
+        std::fs::write(dir.join("part-1.lance"), b"partition one").unwrap();
+        let partition_files = vec!["part-0.lance".to_string(), "part-1.lance".to_string()];
+
+        let manifest_path = RT
+            .block_on(
+                None,
+                write_shuffle_manifest(&dir_str, "shuffle", &partition_files),
+            )
+            .unwrap()
+            .unwrap();
+
+        // The manifest path comes back joined with `dir_path`, the same way partition
+        // filenames are resolved, rather than a bare filename relative to the cwd.
+        assert_eq!(manifest_path, format!("{dir_str}/shuffle{SHUFFLE_MANIFEST_SUFFIX}"));
+
+        let verified = RT
+            .block_on(
+                None,
+                verify_shuffle_manifest(&dir_str, &manifest_path, &partition_files),
+            )
+            .unwrap();
+        assert!(verified.is_ok());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn verify_shuffle_manifest_rejects_corrupted_partition_file() {
+        let dir = test_scratch_dir("shuffle_manifest_corruption");
+        let dir_str = dir.to_str().unwrap().to_string();
+        std::fs::write(dir.join("part-0.lance"), b"original contents").unwrap();
+        let partition_files = vec!["part-0.lance".to_string()];
+
+        let manifest_path = RT
+            .block_on(
+                None,
+                write_shuffle_manifest(&dir_str, "shuffle", &partition_files),
+            )
+            .unwrap()
+            .unwrap();
+
+        // Corrupt the partition file after the manifest was written.
+        std::fs::write(dir.join("part-0.lance"), b"tampered contents!!").unwrap();
+
+        let result = RT
+            .block_on(
+                None,
+                verify_shuffle_manifest(&dir_str, &manifest_path, &partition_files),
+            )
+            .unwrap();
+        assert!(result.is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn validate_pq_num_bits_accepts_4_and_8() {
+        assert_eq!(validate_pq_num_bits(4).unwrap(), 4);
+        assert_eq!(validate_pq_num_bits(8).unwrap(), 8);
+    }
+
+    #[test]
+    fn validate_pq_num_bits_rejects_other_widths() {
+        assert!(validate_pq_num_bits(5).is_err());
+        assert!(validate_pq_num_bits(16).is_err());
+        assert!(validate_pq_num_bits(0).is_err());
+    }
+
+    #[test]
+    fn validate_transform_format_accepts_known_formats() {
+        assert_eq!(validate_transform_format("lance").unwrap(), "lance");
+        assert_eq!(validate_transform_format("parquet").unwrap(), "parquet");
+    }
+
+    #[test]
+    fn validate_transform_format_rejects_unknown_formats() {
+        assert!(validate_transform_format("Parquet").is_err());
+        assert!(validate_transform_format("csv").is_err());
+        assert!(validate_transform_format("").is_err());
+    }
+
+    #[test]
+    fn pack_pq_codes_8_bit_is_passthrough() {
+        let codes = vec![1u8, 2, 3, 4];
+        assert_eq!(pack_pq_codes(&codes, 8), codes);
+        assert_eq!(packed_code_width(4, 8), 4);
+    }
+
+    #[test]
+    fn pack_pq_codes_4_bit_packs_two_codes_per_byte() {
+        // Low nibble holds the first code, high nibble the second.
+        let codes = vec![0x3, 0xA, 0x5];
+        let packed = pack_pq_codes(&codes, 4);
+        assert_eq!(packed, vec![0xA3, 0x05]);
+        assert_eq!(packed_code_width(codes.len(), 4), 2);
+
+        let unpacked: Vec<u8> = packed
+            .iter()
+            .flat_map(|byte| [byte & 0x0F, (byte >> 4) & 0x0F])
+            .take(codes.len())
+            .collect();
+        assert_eq!(unpacked, codes);
+    }
+
+    /// Distances from node 0, used as `dist_to` against the small line graph below:
+    /// node 1 is nearest, then 2, then 3, with 4 unreachable from the entry point.
+    fn dist_from_0(id: u32) -> f32 {
+        match id {
+            0 => 0.0,
+            1 => 1.0,
+            2 => 2.0,
+            3 => 3.0,
+            _ => 100.0,
+        }
+    }
+
+    fn edges(ids: &[(u32, f32)]) -> Vec<Neighbor> {
+        ids.iter().map(|&(id, dist)| Neighbor { id, dist }).collect()
+    }
+
+    /// `Neighbor` equality compares distances only, so edge assertions go through
+    /// `(id, dist)` pairs to check the neighbor ids as well.
+    fn id_dists(edges: &[Neighbor]) -> Vec<(u32, f32)> {
+        edges.iter().map(|n| (n.id, n.dist)).collect()
+    }
+
+    fn line_graph(max_edges: usize) -> HnswGraph {
+        // 0 - 1 - 2 - 3, node 4 left isolated (unreachable from entry point 0).
+        let mut graph = HnswGraph::new(5, max_edges);
+        graph.neighbors[0] = edges(&[(1, 1.0)]);
+        graph.neighbors[1] = edges(&[(0, 1.0), (2, 1.0)]);
+        graph.neighbors[2] = edges(&[(1, 1.0), (3, 1.0)]);
+        graph.neighbors[3] = edges(&[(2, 1.0)]);
+        graph
+    }
+
+    #[test]
+    fn search_finds_ef_nearest_reachable_neighbors() {
+        let graph = line_graph(4);
+        let results = graph.search(dist_from_0, &[0], 2);
+        let mut ids: Vec<u32> = results.into_iter().map(|n| n.id).collect();
+        ids.sort();
+        // Only the 2 closest of the 4 reachable nodes are kept; the unreachable
+        // node 4 never gets visited.
+        assert_eq!(ids, vec![0, 1]);
+    }
+
+    #[test]
+    fn search_does_not_visit_unreachable_nodes() {
+        let graph = line_graph(4);
+        let results = graph.search(dist_from_0, &[0], 10);
+        let ids: Vec<u32> = results.into_iter().map(|n| n.id).collect();
+        assert!(!ids.contains(&4));
+        assert_eq!(ids.len(), 4);
+    }
+
+    #[test]
+    fn search_with_no_entry_points_returns_empty() {
+        let graph = line_graph(4);
+        let results = graph.search(dist_from_0, &[], 5);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn connect_caps_edges_at_max_edges_and_adds_reverse_edges() {
+        let mut graph = HnswGraph::new(4, 2);
+        let mut candidates = BinaryHeap::new();
+        candidates.push(Neighbor { id: 1, dist: 1.0 });
+        candidates.push(Neighbor { id: 2, dist: 2.0 });
+        candidates.push(Neighbor { id: 3, dist: 3.0 });
+
+        graph.connect(0, candidates);
+
+        // Only the 2 closest candidates are kept as out-edges.
+        assert_eq!(id_dists(&graph.neighbors[0]), vec![(1, 1.0), (2, 2.0)]);
+        // Each kept candidate gets a reverse edge back to node 0.
+        assert_eq!(id_dists(&graph.neighbors[1]), vec![(0, 1.0)]);
+        assert_eq!(id_dists(&graph.neighbors[2]), vec![(0, 2.0)]);
+        assert!(graph.neighbors[3].is_empty());
+    }
+
+    #[test]
+    fn connect_evicts_farthest_reverse_edge_when_neighbor_is_full() {
+        let mut graph = HnswGraph::new(3, 1);
+        // Node 1 already has a far edge to node 9 (dist 5.0).
+        graph.neighbors[1] = edges(&[(9, 5.0)]);
+
+        let mut candidates = BinaryHeap::new();
+        candidates.push(Neighbor { id: 1, dist: 1.0 });
+        graph.connect(0, candidates);
+
+        // The new edge to node 0 (dist 1.0) is closer than the existing edge to
+        // node 9 (dist 5.0), so the farther edge is evicted, not the older one.
+        assert_eq!(id_dists(&graph.neighbors[1]), vec![(0, 1.0)]);
+    }
+
+    #[test]
+    fn connect_keeps_closer_existing_reverse_edge_over_new_farther_one() {
+        let mut graph = HnswGraph::new(3, 1);
+        // Node 1 already has a close edge to node 9 (dist 0.5).
+        graph.neighbors[1] = edges(&[(9, 0.5)]);
+
+        let mut candidates = BinaryHeap::new();
+        candidates.push(Neighbor { id: 1, dist: 4.0 });
+        graph.connect(0, candidates);
+
+        // The new edge to node 0 (dist 4.0) is farther than the existing edge to
+        // node 9 (dist 0.5), so the existing closer edge is kept instead.
+        assert_eq!(id_dists(&graph.neighbors[1]), vec![(9, 0.5)]);
+    }
+}